@@ -12,27 +12,65 @@ use super::{
     constants::{SN_AUTHD_ENDPOINT_HOST, SN_AUTHD_ENDPOINT_PORT},
     Safe,
 };
-use crate::api::ipc::IpcMsg;
+use crate::api::ipc::{AppPermissions, IpcMsg, IpcResp};
 use crate::{Error, Result};
+use data_encoding::BASE64URL_NOPAD;
 use log::{debug, info};
+use rand::Rng;
 use serde_json::json;
+use sha2::{Digest, Sha256};
 
-// Method for requesting application's authorisation
+// Method for requesting application's authorisation.
 const SN_AUTHD_METHOD_AUTHORISE: &str = "authorise";
+// Method for revoking a previously authorised application.
+const SN_AUTHD_METHOD_REVOKE: &str = "revoke";
+// Method for refreshing credentials that were previously granted.
+const SN_AUTHD_METHOD_REFRESH: &str = "refresh";
+
+// PKCE challenge method we advertise in the `AuthReq`.
+const PKCE_CHALLENGE_METHOD_S256: &str = "S256";
+
+// Alphabet used to generate the PKCE `code_verifier`, restricted to the "unreserved" URL-safe
+// characters as per RFC 7636.
+const CODE_VERIFIER_ALPHABET: &[u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-._~";
+const CODE_VERIFIER_LEN: usize = 64;
 
 impl Safe {
     // Generate an authorisation request string and send it to a SAFE Authenticator.
-    // It returns the credentials necessary to connect to the network, encoded in a single string.
+    //
+    // The `code_challenge` committed to in the request is derived from a `code_verifier` that
+    // never leaves this function; authd is expected to echo `code_challenge` back unchanged in
+    // its `AuthGranted` response, which is re-derived here from the held `code_verifier` and
+    // constant-time-compared against what was sent, so a response that doesn't correspond to
+    // our request is rejected locally. Returns the credentials necessary to connect to the
+    // network, encoded in a single string, together with the scope of permissions that was
+    // actually granted (which may be a subset of what was requested).
     pub async fn auth_app(
         app_id: &str,
         app_name: &str,
         app_vendor: &str,
+        permissions: Option<AppPermissions>,
         endpoint: Option<&str>,
-    ) -> Result<String> {
-        // TODO: allow to accept all type of permissions to be passed as args to this API
+    ) -> Result<(String, AppPermissions)> {
         info!("Sending authorisation request to SAFE Authenticator...");
 
-        let request = IpcMsg::new_auth_req(app_id, app_name, app_vendor);
+        let permissions = permissions.unwrap_or_default();
+        let code_verifier = gen_code_verifier();
+        let code_challenge = derive_code_challenge(&code_verifier);
+
+        let request = IpcMsg::new_auth_req(
+            app_id,
+            app_name,
+            app_vendor,
+            &permissions,
+            &code_challenge,
+            PKCE_CHALLENGE_METHOD_S256,
+        );
+        let req_id = match &request {
+            IpcMsg::Req { req_id, .. } => *req_id,
+            _ => unreachable!("new_auth_req always builds a Req message"),
+        };
         let auth_req_str = request.to_string()?;
         debug!(
             "Authorisation request generated successfully: {}",
@@ -44,9 +82,29 @@ impl Safe {
 
         // Check if the app has been authorised
         match IpcMsg::from_string(&auth_res) {
-            Ok(IpcMsg::Resp(_ipc_resp)) => {
-                info!("Application was authorised: {:?}", auth_res);
-                Ok(auth_res)
+            Ok(IpcMsg::Resp {
+                req_id: resp_req_id,
+                response: IpcResp::Auth(Ok(auth_granted)),
+            }) => {
+                if resp_req_id != req_id {
+                    return Err(Error::AuthError(
+                        "Authorisation response does not match the originating request"
+                            .to_string(),
+                    ));
+                }
+                if !constant_time_eq(
+                    derive_code_challenge(&code_verifier).as_bytes(),
+                    auth_granted.code_challenge.as_bytes(),
+                ) {
+                    return Err(Error::AuthError(
+                        "Authorisation response failed PKCE verification".to_string(),
+                    ));
+                }
+                info!(
+                    "Application was authorised with permissions: {:?}",
+                    auth_granted.granted_permissions
+                );
+                Ok((auth_res, auth_granted.granted_permissions))
             }
             Ok(other) => {
                 info!("Unexpected messages received: {:?}", other);
@@ -69,19 +127,53 @@ impl Safe {
     pub async fn connect(&mut self, auth_credentials: Option<&str>) -> Result<()> {
         self.safe_client.connect(auth_credentials).await
     }
+
+    // Ask authd to revoke a previously authorised application, invalidating any credentials it
+    // had been granted.
+    pub async fn revoke_app(app_id: &str, endpoint: Option<&str>) -> Result<()> {
+        info!("Sending revocation request to SAFE Authenticator...");
+        let _: String = send_authd_request(
+            &authd_service_url(endpoint),
+            SN_AUTHD_METHOD_REVOKE,
+            json!(app_id),
+        )
+        .await?;
+
+        info!("Application was revoked successfully");
+        Ok(())
+    }
+
+    // Ask authd to rotate a set of previously granted credentials, mirroring an OAuth
+    // refresh-grant: the old credentials are exchanged for a new set without requiring the user
+    // to go through the full authorisation flow again.
+    pub async fn refresh_auth(old_credentials: &str, endpoint: Option<&str>) -> Result<String> {
+        info!("Sending credentials refresh request to SAFE Authenticator...");
+        let refreshed_credentials = send_authd_request::<String>(
+            &authd_service_url(endpoint),
+            SN_AUTHD_METHOD_REFRESH,
+            json!(old_credentials),
+        )
+        .await?;
+
+        info!("Credentials were refreshed successfully");
+        Ok(refreshed_credentials)
+    }
+}
+
+// Resolve the authd service URL to use, falling back to the default host/port when none is given.
+fn authd_service_url(endpoint: Option<&str>) -> String {
+    match endpoint {
+        None => format!("{}:{}", SN_AUTHD_ENDPOINT_HOST, SN_AUTHD_ENDPOINT_PORT),
+        Some(endpoint) => endpoint.to_string(),
+    }
 }
 
 // Sends an authorisation request string to the SAFE Authenticator daemon endpoint.
 // It returns the credentials necessary to connect to the network, encoded in a single string.
 async fn send_app_auth_req(auth_req_str: &str, endpoint: Option<&str>) -> Result<String> {
-    let authd_service_url = match endpoint {
-        None => format!("{}:{}", SN_AUTHD_ENDPOINT_HOST, SN_AUTHD_ENDPOINT_PORT,),
-        Some(endpoint) => endpoint.to_string(),
-    };
-
     info!("Sending authorisation request to SAFE Authenticator...");
     let authd_response = send_authd_request::<String>(
-        &authd_service_url,
+        &authd_service_url(endpoint),
         SN_AUTHD_METHOD_AUTHORISE,
         json!(auth_req_str),
     )
@@ -90,3 +182,29 @@ async fn send_app_auth_req(auth_req_str: &str, endpoint: Option<&str>) -> Result
     info!("SAFE authorisation response received!");
     Ok(authd_response)
 }
+
+// Generate a high-entropy, URL-safe PKCE code verifier.
+fn gen_code_verifier() -> String {
+    let mut rng = rand::thread_rng();
+    (0..CODE_VERIFIER_LEN)
+        .map(|_| {
+            let idx = rng.gen_range(0, CODE_VERIFIER_ALPHABET.len());
+            CODE_VERIFIER_ALPHABET[idx] as char
+        })
+        .collect()
+}
+
+// Derive the PKCE code challenge from a code verifier: BASE64URL_NOPAD(SHA256(code_verifier)).
+fn derive_code_challenge(code_verifier: &str) -> String {
+    let digest = Sha256::digest(code_verifier.as_bytes());
+    BASE64URL_NOPAD.encode(&digest)
+}
+
+// Compare two byte slices in constant time, to avoid leaking timing information about how much
+// of the expected PKCE code challenge was guessed correctly.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
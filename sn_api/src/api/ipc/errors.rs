@@ -9,6 +9,24 @@
 use serde::{Deserialize, Serialize};
 //use threshold_crypto::error::FromBytesError;
 
+impl From<data_encoding::DecodeError> for IpcError {
+    fn from(_err: data_encoding::DecodeError) -> Self {
+        Self::EncodeDecodeError
+    }
+}
+
+impl From<bincode::Error> for IpcError {
+    fn from(_err: bincode::Error) -> Self {
+        Self::EncodeDecodeError
+    }
+}
+
+impl From<bs58::decode::Error> for IpcError {
+    fn from(_err: bs58::decode::Error) -> Self {
+        Self::EncodeDecodeError
+    }
+}
+
 /// Ipc error.
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
 pub enum IpcError {
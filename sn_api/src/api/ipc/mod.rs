@@ -16,17 +16,19 @@ mod errors;
 pub use self::errors::IpcError;
 pub use self::req::{
     // AppExchangeInfo,
+    AppPermissions,
     AuthReq,
+    ContainerPermissions,
     // ContainersReq,
     IpcReq,
-    // Permission,
+    Permission,
     // ShareMap,
     // ShareMapReq,
 };
 pub use self::resp::{AuthGranted, IpcResp};
 
 use bincode::{deserialize, serialize};
-use data_encoding::BASE32_NOPAD;
+use data_encoding::{BASE32_NOPAD, BASE64URL_NOPAD, BASE64_NOPAD, HEXLOWER, HEXUPPER};
 // #[cfg(any(test, feature = "testing"))]
 // use rand::{self};
 use serde::{Deserialize, Serialize};
@@ -64,23 +66,48 @@ pub enum IpcMsg {
     Err(IpcError),
 }
 
-/// Encode `IpcMsg` into string, using base32 encoding.
+/// Encode `IpcMsg` into string, using base32 encoding (the multibase `b` prefix).
 pub fn encode_msg(msg: &IpcMsg) -> Result<String, IpcError> {
-    // We also add a multicodec compatible prefix. For more details please follow
-    // https://github.com/multiformats/multicodec/blob/master/table.csv
-    Ok(format!("b{}", BASE32_NOPAD.encode(&serialize(&msg)?)))
+    encode_msg_with_base(msg, 'b')
 }
 
-/// Decode `IpcMsg` encoded with base32 encoding.
+/// Encode `IpcMsg` into a multibase-prefixed string, using the requested base.
+///
+/// We add a multicodec/multibase compatible prefix. For more details please follow
+/// https://github.com/multiformats/multibase#multibase-table
+/// Supported prefixes: `f`/`F` (base16 lower/upper), `b`/`B` (base32 no-pad), `m` (base64
+/// standard no-pad), `u` (base64url no-pad), `z` (base58btc).
+pub fn encode_msg_with_base(msg: &IpcMsg, base: char) -> Result<String, IpcError> {
+    let bytes = serialize(&msg)?;
+    let encoded = match base {
+        'f' => HEXLOWER.encode(&bytes),
+        'F' => HEXUPPER.encode(&bytes),
+        'b' | 'B' => BASE32_NOPAD.encode(&bytes),
+        'm' => BASE64_NOPAD.encode(&bytes),
+        'u' => BASE64URL_NOPAD.encode(&bytes),
+        'z' => bs58::encode(&bytes).into_string(),
+        _ => return Err(IpcError::EncodeDecodeError),
+    };
+
+    Ok(format!("{}{}", base, encoded))
+}
+
+/// Decode `IpcMsg` encoded with `encode_msg`/`encode_msg_with_base`, dispatching on the
+/// multibase prefix character.
 pub fn decode_msg(encoded: &str) -> Result<IpcMsg, IpcError> {
     info!("ENCODED MSG STRING: {:?}", encoded);
     let mut chars = encoded.chars();
-    let decoded = match chars.next().ok_or(IpcError::InvalidMsg)? {
-        // Encoded as base32
-        'b' | 'B' => BASE32_NOPAD.decode(chars.as_str().as_bytes())?,
-        // Fail if not encoded as base32
+    let prefix = chars.next().ok_or(IpcError::InvalidMsg)?;
+    let rest = chars.as_str();
+    let decoded = match prefix {
+        'f' => HEXLOWER.decode(rest.as_bytes())?,
+        'F' => HEXUPPER.decode(rest.as_bytes())?,
+        'b' | 'B' => BASE32_NOPAD.decode(rest.as_bytes())?,
+        'm' => BASE64_NOPAD.decode(rest.as_bytes())?,
+        'u' => BASE64URL_NOPAD.decode(rest.as_bytes())?,
+        'z' => bs58::decode(rest).into_vec()?,
         _ => {
-            debug!("This didn't start with B, wth...");
+            debug!("Unsupported multibase prefix: {:?}", prefix);
             return Err(IpcError::EncodeDecodeError);
         }
     };
@@ -96,3 +123,121 @@ pub fn gen_req_id() -> u32 {
     // Generate the number in range 1..MAX inclusive.
     rand::thread_rng().gen_range(0, u32::max_value()) + 1
 }
+
+impl IpcMsg {
+    /// Build a new authorisation request, tagged with a freshly generated request ID and a
+    /// PKCE code challenge that `authd` is expected to echo back in its response.
+    pub fn new_auth_req(
+        app_id: &str,
+        app_name: &str,
+        app_vendor: &str,
+        permissions: &AppPermissions,
+        code_challenge: &str,
+        code_challenge_method: &str,
+    ) -> Self {
+        IpcMsg::Req {
+            req_id: gen_req_id(),
+            request: IpcReq::Auth(AuthReq {
+                app_id: app_id.to_string(),
+                app_name: app_name.to_string(),
+                app_vendor: app_vendor.to_string(),
+                app_container: permissions.app_container,
+                containers: permissions.containers.clone(),
+                code_challenge: code_challenge.to_string(),
+                code_challenge_method: code_challenge_method.to_string(),
+            }),
+        }
+    }
+
+    /// Encode this message into a multibase-encoded string ready to be handed to authd.
+    #[allow(clippy::inherent_to_string)]
+    pub fn to_string(&self) -> Result<String, IpcError> {
+        encode_msg(self)
+    }
+
+    /// Decode a message previously produced by `to_string`.
+    pub fn from_string(encoded: &str) -> Result<Self, IpcError> {
+        decode_msg(encoded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_msg() -> IpcMsg {
+        IpcMsg::Revoked {
+            app_id: "net.maidsafe.cli".to_string(),
+        }
+    }
+
+    #[test]
+    fn round_trip_base16_lower() {
+        let encoded = encode_msg_with_base(&sample_msg(), 'f').unwrap();
+        assert!(encoded.starts_with('f'));
+        assert_eq!(decode_msg(&encoded).unwrap(), sample_msg());
+    }
+
+    #[test]
+    fn round_trip_base16_upper() {
+        let encoded = encode_msg_with_base(&sample_msg(), 'F').unwrap();
+        assert!(encoded.starts_with('F'));
+        assert_eq!(decode_msg(&encoded).unwrap(), sample_msg());
+    }
+
+    #[test]
+    fn round_trip_base32_lower() {
+        let encoded = encode_msg_with_base(&sample_msg(), 'b').unwrap();
+        assert!(encoded.starts_with('b'));
+        assert_eq!(decode_msg(&encoded).unwrap(), sample_msg());
+    }
+
+    #[test]
+    fn round_trip_base32_upper() {
+        let encoded = encode_msg_with_base(&sample_msg(), 'B').unwrap();
+        assert!(encoded.starts_with('B'));
+        assert_eq!(decode_msg(&encoded).unwrap(), sample_msg());
+    }
+
+    #[test]
+    fn round_trip_base64() {
+        let encoded = encode_msg_with_base(&sample_msg(), 'm').unwrap();
+        assert!(encoded.starts_with('m'));
+        assert_eq!(decode_msg(&encoded).unwrap(), sample_msg());
+    }
+
+    #[test]
+    fn round_trip_base64url() {
+        let encoded = encode_msg_with_base(&sample_msg(), 'u').unwrap();
+        assert!(encoded.starts_with('u'));
+        assert_eq!(decode_msg(&encoded).unwrap(), sample_msg());
+    }
+
+    #[test]
+    fn round_trip_base58btc() {
+        let encoded = encode_msg_with_base(&sample_msg(), 'z').unwrap();
+        assert!(encoded.starts_with('z'));
+        assert_eq!(decode_msg(&encoded).unwrap(), sample_msg());
+    }
+
+    #[test]
+    fn encode_msg_defaults_to_base32() {
+        assert_eq!(
+            encode_msg(&sample_msg()).unwrap(),
+            encode_msg_with_base(&sample_msg(), 'b').unwrap()
+        );
+    }
+
+    #[test]
+    fn decode_msg_rejects_unknown_prefix() {
+        assert_eq!(decode_msg("x2y3z4").unwrap_err(), IpcError::EncodeDecodeError);
+    }
+
+    #[test]
+    fn encode_msg_with_base_rejects_unknown_base() {
+        assert_eq!(
+            encode_msg_with_base(&sample_msg(), 'x').unwrap_err(),
+            IpcError::EncodeDecodeError
+        );
+    }
+}
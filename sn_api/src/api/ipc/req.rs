@@ -0,0 +1,67 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Ipc request.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub enum IpcReq {
+    /// Authentication request.
+    Auth(AuthReq),
+}
+
+/// A single permission that can be requested/granted over a container.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Permission {
+    /// Permission to read data.
+    Read,
+    /// Permission to insert new data.
+    Insert,
+    /// Permission to update existing data.
+    Update,
+    /// Permission to delete data.
+    Delete,
+    /// Permission to manage other apps' permissions on the container.
+    ManagePermissions,
+}
+
+/// Map of container name to the set of `Permission`s requested/granted for it.
+pub type ContainerPermissions = HashMap<String, HashSet<Permission>>;
+
+/// The authorisation scope an application is requesting: which containers it wants access to
+/// and at what permission level, plus whether it needs its own dedicated container.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub struct AppPermissions {
+    /// Per-container permissions being requested.
+    pub containers: ContainerPermissions,
+    /// Whether the app requires its own dedicated container.
+    pub app_container: bool,
+}
+
+/// Represents an authorisation request from an application to the Authenticator.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct AuthReq {
+    /// The id of the application requesting authorisation.
+    pub app_id: String,
+    /// The friendly name of the application.
+    pub app_name: String,
+    /// The vendor of the application.
+    pub app_vendor: String,
+    /// Whether the app requires its own dedicated container.
+    pub app_container: bool,
+    /// Per-container permissions being requested.
+    pub containers: ContainerPermissions,
+    /// PKCE code challenge, derived from the `code_verifier` the application is holding onto,
+    /// that `authd` must echo back in its granted response so the caller can verify the
+    /// response actually corresponds to this request.
+    pub code_challenge: String,
+    /// The method used to derive `code_challenge` from the `code_verifier` (currently always
+    /// `"S256"`).
+    pub code_challenge_method: String,
+}
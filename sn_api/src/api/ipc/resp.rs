@@ -0,0 +1,34 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{AppPermissions, BootstrapConfig, IpcError};
+use serde::{Deserialize, Serialize};
+
+/// Ipc response.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub enum IpcResp {
+    /// Response to an authentication request.
+    Auth(Result<AuthGranted, IpcError>),
+    /// Response to an unregistered-client bootstrap request.
+    Unregistered(Result<BootstrapConfig, IpcError>),
+}
+
+/// Credentials and connection info granted to an application by the Authenticator.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct AuthGranted {
+    /// Serialised credentials (keypair) issued to the application.
+    pub credentials: String,
+    /// Bootstrap info needed to connect the app to the network.
+    pub bootstrap_config: BootstrapConfig,
+    /// The PKCE code challenge from the originating `AuthReq`, echoed back so the caller can
+    /// verify this response corresponds to the request it made.
+    pub code_challenge: String,
+    /// The scope of permissions that was actually approved, which may be a subset of what the
+    /// application requested in its `AuthReq`.
+    pub granted_permissions: AppPermissions,
+}
@@ -8,9 +8,15 @@
 // Software.
 
 use crate::{APP_ID, APP_NAME, APP_VENDOR};
+use chacha20poly1305::{
+    aead::{Aead, NewAead},
+    Key, XChaCha20Poly1305, XNonce,
+};
 use log::{debug, info, warn};
-use sn_api::{Keypair, Safe};
+use rand::RngCore;
+use sn_api::{ipc::AppPermissions, Keypair, Safe};
 use std::{
+    env,
     fs::{create_dir_all, File},
     io::{Read, Write},
     path::PathBuf,
@@ -18,59 +24,203 @@ use std::{
 
 const AUTH_CREDENTIALS_FILENAME: &str = "credentials";
 
-pub async fn authorise_cli(endpoint: Option<String>, is_self_authing: bool) -> Result<(), String> {
-    let (mut file, file_path) = create_credentials_file()?;
-    println!("Authorising CLI application...");
+// Suffix appended to a profile's credentials file name to get its granted-permissions sidecar
+// file name, e.g. `credentials.work` -> `credentials.work.permissions`.
+const GRANTED_PERMISSIONS_SUFFIX: &str = ".permissions";
+
+// Name of the profile used when none is explicitly selected.
+const DEFAULT_PROFILE_NAME: &str = "default";
+
+// File (within the credentials folder) recording which profile is currently active, when one
+// has been selected with `select_profile` rather than via the `SAFE_CLI_PROFILE` env var.
+const ACTIVE_PROFILE_MARKER_FILENAME: &str = "active_profile";
+
+// Env var that, when set, selects the active credentials profile, taking precedence over the
+// stored active-profile marker.
+const SAFE_CLI_PROFILE_ENV_VAR: &str = "SAFE_CLI_PROFILE";
+
+// Env var that, when set, is used as the passphrase to decrypt/encrypt the credentials file
+// instead of prompting interactively.
+const SAFE_CLI_PASSPHRASE_ENV_VAR: &str = "SAFE_CLI_PASSPHRASE";
+
+// Marks an encrypted-at-rest credentials file; a plaintext legacy file never starts with this.
+const ENCRYPTED_FILE_MAGIC: &[u8; 4] = b"SAFE";
+// Version of the encrypted file format, so the header can evolve without breaking old files.
+const ENCRYPTED_FILE_VERSION: u8 = 1;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+pub async fn authorise_cli(
+    endpoint: Option<String>,
+    is_self_authing: bool,
+    permissions: Option<AppPermissions>,
+    encrypt_at_rest: bool,
+    profile: Option<String>,
+) -> Result<(), String> {
+    let profile = resolve_profile(profile)?;
+    let (mut file, file_path) = create_credentials_file(&profile)?;
+    println!("Authorising CLI application for profile '{}'...", profile);
     if !is_self_authing {
         println!("Note you can use this CLI from another console to authorise it with 'auth allow' command. Alternativelly, you can also use '--self-auth' flag with 'auth unlock' command to automatically self authorise the CLI app.");
     }
     println!("Waiting for authorising response from authd...");
-    let app_keypair = Safe::auth_app(APP_ID, APP_NAME, APP_VENDOR, endpoint.as_deref())
-        .await
-        .map_err(|err| format!("Application authorisation failed: {}", err))?;
+    let (app_keypair, granted_permissions) = Safe::auth_app(
+        APP_ID,
+        APP_NAME,
+        APP_VENDOR,
+        permissions,
+        endpoint.as_deref(),
+    )
+    .await
+    .map_err(|err| format!("Application authorisation failed: {}", err))?;
+
+    let serialised_keypair = wrap_credentials_for_storage(&app_keypair)?;
 
-    let serialised_keypair = serde_json::to_string(&app_keypair)
-        .map_err(|err| format!("Unable to serialise the credentials obtained: {}", err))?;
+    let bytes_to_write = if encrypt_at_rest {
+        let passphrase = obtain_passphrase()?;
+        encrypt_credentials(serialised_keypair.as_bytes(), &passphrase)?
+    } else {
+        serialised_keypair.into_bytes()
+    };
 
-    file.write_all(serialised_keypair.as_bytes())
-        .map_err(|err| {
-            format!(
-                "Unable to write credentials in {}: {}",
-                file_path.display(),
-                err
-            )
-        })?;
+    file.write_all(&bytes_to_write).map_err(|err| {
+        format!(
+            "Unable to write credentials in {}: {}",
+            file_path.display(),
+            err
+        )
+    })?;
+    write_granted_permissions(&profile, &granted_permissions)?;
 
-    println!("Safe CLI app was successfully authorised");
+    println!(
+        "Safe CLI app was successfully authorised with permissions: {:?}",
+        granted_permissions
+    );
     println!("Credentials were stored in {}", file_path.display());
     Ok(())
 }
 
-pub fn clear_credentials() -> Result<(), String> {
-    let (_, file_path) =
-        create_credentials_file().map_err(|err| format!("Failed to clear credentials. {}", err))?;
+pub fn clear_credentials(profile: Option<String>) -> Result<(), String> {
+    let profile = resolve_profile(profile)?;
+    let (_, file_path) = create_credentials_file(&profile)
+        .map_err(|err| format!("Failed to clear credentials. {}", err))?;
+    let _ = std::fs::remove_file(granted_permissions_file_path(&profile)?);
 
     println!(
-        "Credentials were succesfully cleared from {}",
+        "Credentials for profile '{}' were succesfully cleared from {}",
+        profile,
         file_path.display()
     );
     Ok(())
 }
 
+// Ask authd to revoke the CLI application, then clear the locally stored credentials since
+// they're no longer of any use once authd has invalidated them.
+pub async fn revoke(endpoint: Option<String>, profile: Option<String>) -> Result<(), String> {
+    let profile = resolve_profile(profile)?;
+    println!(
+        "Revoking authorisation for the CLI application (profile '{}')...",
+        profile
+    );
+    Safe::revoke_app(APP_ID, endpoint.as_deref())
+        .await
+        .map_err(|err| format!("Failed to revoke application: {}", err))?;
+
+    clear_credentials(Some(profile))?;
+    println!("Application was successfully revoked");
+    Ok(())
+}
+
+// List the names of the credential profiles found in the CLI's data folder, i.e. every
+// `credentials.<profile>` file plus `default` if the unsuffixed `credentials` file exists.
+pub fn list_profiles() -> Result<Vec<String>, String> {
+    let (credentials_folder, _) = get_credentials_file_path(DEFAULT_PROFILE_NAME)?;
+    if !credentials_folder.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut profiles = vec![];
+    for entry in std::fs::read_dir(&credentials_folder)
+        .map_err(|err| format!("Failed to read credentials folder: {}", err))?
+    {
+        let entry = entry.map_err(|err| format!("Failed to read credentials folder: {}", err))?;
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        if file_name.ends_with(GRANTED_PERMISSIONS_SUFFIX) {
+            continue;
+        }
+        if file_name == AUTH_CREDENTIALS_FILENAME {
+            profiles.push(DEFAULT_PROFILE_NAME.to_string());
+        } else if let Some(profile) =
+            file_name.strip_prefix(&format!("{}.", AUTH_CREDENTIALS_FILENAME))
+        {
+            profiles.push(profile.to_string());
+        }
+    }
+
+    profiles.sort();
+    Ok(profiles)
+}
+
+// Mark `profile` as the active one, by writing its name to the active-profile marker file. This
+// is overridden at resolution time by the `SAFE_CLI_PROFILE` env var.
+pub fn select_profile(profile: &str) -> Result<(), String> {
+    let (credentials_folder, _) = get_credentials_file_path(DEFAULT_PROFILE_NAME)?;
+    if !credentials_folder.exists() {
+        create_dir_all(&credentials_folder)
+            .map_err(|err| format!("Couldn't create project's local data folder: {}", err))?;
+    }
+
+    std::fs::write(
+        credentials_folder.join(ACTIVE_PROFILE_MARKER_FILENAME),
+        profile,
+    )
+    .map_err(|err| format!("Failed to select active profile: {}", err))?;
+
+    println!("Profile '{}' is now the active profile", profile);
+    Ok(())
+}
+
 // Attempt to connect with credentials if found and valid,
 // otherwise it creates a read only connection.
-// Returns the app's keypair if connection was succesfully made with credentials,
-// otherwise it returns 'None' if conneciton is read only.
-pub async fn connect(safe: &mut Safe) -> Result<Option<Keypair>, String> {
-    debug!("Connecting...");
+// `profile` selects which credentials profile to connect with, following the same
+// explicit/env-var/marker-file/default precedence as `resolve_profile`.
+// Returns the app's keypair, the name of the profile connected with, and the scope of
+// permissions that was granted when the app was authorised (if that's recorded for the
+// profile), if connection was succesfully made with credentials. Returns `None` if the
+// connection is read-only.
+pub async fn connect(
+    safe: &mut Safe,
+    profile: Option<String>,
+) -> Result<Option<(Keypair, String, Option<AppPermissions>)>, String> {
+    let profile = resolve_profile(profile)?;
+    debug!("Connecting with profile '{}'...", profile);
+    let granted_permissions = read_granted_permissions(&profile)?;
 
-    let app_keypair = match get_credentials_file_path() {
+    let loaded_credentials = match get_credentials_file_path(&profile) {
         Ok((_, file_path)) => {
             if let Ok(mut file) = File::open(&file_path) {
-                let mut credentials = String::new();
-                match file.read_to_string(&mut credentials) {
-                    Ok(_) if credentials.is_empty() => None,
+                let mut raw_contents = Vec::new();
+                match file.read_to_end(&mut raw_contents) {
+                    Ok(_) if raw_contents.is_empty() => None,
                     Ok(_) => {
+                        let is_encrypted = raw_contents.starts_with(ENCRYPTED_FILE_MAGIC);
+                        let credentials = if is_encrypted {
+                            let passphrase = obtain_passphrase()?;
+                            let plaintext = decrypt_credentials(&raw_contents, &passphrase)?;
+                            let credentials = String::from_utf8(plaintext).map_err(|err| {
+                                format!("Decrypted credentials are not valid UTF-8: {}", err)
+                            })?;
+                            (credentials, Some(passphrase))
+                        } else {
+                            let credentials =
+                                String::from_utf8(raw_contents).map_err(|err| {
+                                    format!("Credentials file is not valid UTF-8: {}", err)
+                                })?;
+                            (credentials, None)
+                        };
+                        let (credentials, passphrase) = credentials;
                         let keypair = serde_json::from_str(&credentials).map_err(|err| {
                             format!(
                                 "Unable to parse the credentials read from {}: {}",
@@ -78,7 +228,7 @@ pub async fn connect(safe: &mut Safe) -> Result<Option<Keypair>, String> {
                                 err
                             )
                         })?;
-                        Some(keypair)
+                        Some((keypair, credentials, passphrase))
                     }
                     Err(err) => {
                         debug!(
@@ -96,13 +246,31 @@ pub async fn connect(safe: &mut Safe) -> Result<Option<Keypair>, String> {
         Err(_) => None,
     };
 
+    let app_keypair = loaded_credentials
+        .as_ref()
+        .map(|(keypair, _, _)| keypair.clone());
     let found_app_keypair = app_keypair.is_some();
     if !found_app_keypair {
         info!("No credentials found for CLI, connecting with read-only access...");
     }
 
     match safe.connect(app_keypair.clone(), None).await {
-        Err(_) if found_app_keypair => {
+        Err(err) if found_app_keypair => {
+            if let Some((_, raw_credentials, passphrase)) = &loaded_credentials {
+                if let Some(refreshed_keypair) = try_refresh_credentials(
+                    &profile,
+                    raw_credentials,
+                    passphrase.as_deref(),
+                    &err.to_string(),
+                )
+                .await?
+                {
+                    if safe.connect(Some(refreshed_keypair.clone()), None).await.is_ok() {
+                        return Ok(Some((refreshed_keypair, profile, granted_permissions)));
+                    }
+                }
+            }
+
             warn!("Credentials found for CLI are invalid, connecting with read-only access...");
             safe.connect(None, None)
                 .await
@@ -111,13 +279,110 @@ pub async fn connect(safe: &mut Safe) -> Result<Option<Keypair>, String> {
             Ok(None)
         }
         Err(err) => Err(format!("Failed to connect: {}", err)),
-        Ok(()) => Ok(app_keypair),
+        Ok(()) => Ok(app_keypair.map(|keypair| (keypair, profile, granted_permissions))),
     }
 }
 
+// Returns true if the error that `Safe::connect` failed with looks like the credentials have
+// expired or been revoked, as opposed to e.g. a network connectivity problem (including authd
+// itself being unreachable), in which case it's worth trying to refresh them with authd rather
+// than immediately falling back to read-only. Deliberately narrower than a bare "auth" substring
+// match, which would also fire on unrelated failures that merely mention "authd"/"Authenticator".
+fn is_auth_expiry_error(err: &str) -> bool {
+    let lowercased = err.to_lowercase();
+    lowercased.contains("expired") || lowercased.contains("revoked") || lowercased.contains("unauthorised")
+}
+
+// Attempt to rotate credentials that `Safe::connect` just rejected, persisting the new ones in
+// the credentials file on success. Returns `None` (rather than an error) when a refresh isn't
+// warranted or authd couldn't grant one, so the caller can fall back to read-only access.
+//
+// `passphrase` is `Some` when the credentials file being replaced was encrypted-at-rest; the
+// refreshed credentials are then re-encrypted with that same passphrase so the refresh can't
+// silently downgrade the file to plaintext.
+async fn try_refresh_credentials(
+    profile: &str,
+    raw_credentials: &str,
+    passphrase: Option<&str>,
+    connect_err: &str,
+) -> Result<Option<Keypair>, String> {
+    if !is_auth_expiry_error(connect_err) {
+        return Ok(None);
+    }
+
+    info!("Credentials appear to have expired, attempting to refresh them with authd...");
+    // `raw_credentials` is the on-disk file content, which carries the extra layer of
+    // JSON-string quoting `authorise_cli` adds before writing; strip it back off so authd
+    // receives the literal wire-format credentials string it originally issued.
+    let on_wire_credentials = unwrap_stored_credentials(raw_credentials)?;
+    let refreshed_credentials = match Safe::refresh_auth(&on_wire_credentials, None).await {
+        Ok(credentials) => credentials,
+        Err(err) => {
+            debug!("Unable to refresh credentials: {}", err);
+            return Ok(None);
+        }
+    };
+
+    // Re-apply the same quoting before parsing/persisting, so the credentials file stays in the
+    // format `connect` (and a subsequent refresh) expects to read back.
+    let stored_credentials = wrap_credentials_for_storage(&refreshed_credentials)?;
+    let refreshed_keypair = serde_json::from_str(&stored_credentials)
+        .map_err(|err| format!("Unable to parse refreshed credentials: {}", err))?;
+
+    let bytes_to_write = match passphrase {
+        Some(passphrase) => encrypt_credentials(stored_credentials.as_bytes(), passphrase)?,
+        None => stored_credentials.into_bytes(),
+    };
+
+    let (mut file, file_path) = create_credentials_file(profile)?;
+    file.write_all(&bytes_to_write).map_err(|err| {
+        format!(
+            "Unable to write refreshed credentials in {}: {}",
+            file_path.display(),
+            err
+        )
+    })?;
+    info!("Refreshed credentials were stored in {}", file_path.display());
+
+    Ok(Some(refreshed_keypair))
+}
+
+// Strip the JSON-string quoting `wrap_credentials_for_storage` adds before persisting
+// credentials, recovering the literal wire-format string that was originally issued by authd.
+fn unwrap_stored_credentials(stored_credentials: &str) -> Result<String, String> {
+    serde_json::from_str(stored_credentials)
+        .map_err(|err| format!("Unable to parse stored credentials: {}", err))
+}
+
+// Add a layer of JSON-string quoting around a raw wire-format credentials string before it's
+// written to (or re-read from) the credentials file, so the file always holds valid JSON rather
+// than an arbitrary opaque string.
+fn wrap_credentials_for_storage(raw_credentials: &str) -> Result<String, String> {
+    serde_json::to_string(raw_credentials)
+        .map_err(|err| format!("Unable to serialise the credentials obtained: {}", err))
+}
+
 // Private helpers
 
-fn get_credentials_file_path() -> Result<(PathBuf, PathBuf), String> {
+// Resolve which profile to use: an explicitly passed profile takes precedence, then the
+// `SAFE_CLI_PROFILE` env var, then the stored active-profile marker, falling back to `default`.
+fn resolve_profile(profile: Option<String>) -> Result<String, String> {
+    if let Some(profile) = profile {
+        return Ok(profile);
+    }
+    if let Ok(profile) = env::var(SAFE_CLI_PROFILE_ENV_VAR) {
+        return Ok(profile);
+    }
+
+    let (credentials_folder, _) = get_credentials_file_path(DEFAULT_PROFILE_NAME)?;
+    let marker_path = credentials_folder.join(ACTIVE_PROFILE_MARKER_FILENAME);
+    match std::fs::read_to_string(&marker_path) {
+        Ok(profile) => Ok(profile.trim().to_string()),
+        Err(_) => Ok(DEFAULT_PROFILE_NAME.to_string()),
+    }
+}
+
+fn get_credentials_file_path(profile: &str) -> Result<(PathBuf, PathBuf), String> {
     let mut project_data_path =
         dirs_next::home_dir().ok_or_else(|| "Failed to obtain user's home path".to_string())?;
 
@@ -126,12 +391,56 @@ fn get_credentials_file_path() -> Result<(PathBuf, PathBuf), String> {
 
     let credentials_folder = project_data_path;
 
-    let file_path = credentials_folder.join(AUTH_CREDENTIALS_FILENAME);
+    let file_name = if profile == DEFAULT_PROFILE_NAME {
+        AUTH_CREDENTIALS_FILENAME.to_string()
+    } else {
+        format!("{}.{}", AUTH_CREDENTIALS_FILENAME, profile)
+    };
+    let file_path = credentials_folder.join(file_name);
     Ok((credentials_folder, file_path))
 }
 
-fn create_credentials_file() -> Result<(File, PathBuf), String> {
-    let (credentials_folder, file_path) = get_credentials_file_path()?;
+// Path of the sidecar file recording the scope of permissions granted to a profile's
+// credentials, kept alongside (and named after) that profile's credentials file.
+fn granted_permissions_file_path(profile: &str) -> Result<PathBuf, String> {
+    let (_, credentials_file_path) = get_credentials_file_path(profile)?;
+    let file_name = credentials_file_path
+        .file_name()
+        .ok_or_else(|| "Credentials file path has no file name".to_string())?
+        .to_string_lossy();
+    Ok(credentials_file_path.with_file_name(format!(
+        "{}{}",
+        file_name, GRANTED_PERMISSIONS_SUFFIX
+    )))
+}
+
+// Persist the scope of permissions that authd granted for a profile, so it can be reported
+// again by `connect` in future invocations without re-authorising.
+fn write_granted_permissions(
+    profile: &str,
+    granted_permissions: &AppPermissions,
+) -> Result<(), String> {
+    let file_path = granted_permissions_file_path(profile)?;
+    let serialised = serde_json::to_string(granted_permissions)
+        .map_err(|err| format!("Unable to serialise granted permissions: {}", err))?;
+    std::fs::write(&file_path, serialised)
+        .map_err(|err| format!("Unable to write granted permissions: {}", err))
+}
+
+// Read back the scope of permissions previously recorded by `write_granted_permissions` for a
+// profile, returning `None` if nothing was recorded (e.g. credentials predate this feature).
+fn read_granted_permissions(profile: &str) -> Result<Option<AppPermissions>, String> {
+    let file_path = granted_permissions_file_path(profile)?;
+    match std::fs::read_to_string(&file_path) {
+        Ok(serialised) => serde_json::from_str(&serialised)
+            .map(Some)
+            .map_err(|err| format!("Unable to parse granted permissions: {}", err)),
+        Err(_) => Ok(None),
+    }
+}
+
+fn create_credentials_file(profile: &str) -> Result<(File, PathBuf), String> {
+    let (credentials_folder, file_path) = get_credentials_file_path(profile)?;
     if !credentials_folder.exists() {
         println!("Creating '{}' folder", credentials_folder.display());
         create_dir_all(credentials_folder)
@@ -142,3 +451,232 @@ fn create_credentials_file() -> Result<(File, PathBuf), String> {
 
     Ok((file, file_path))
 }
+
+// Obtain the passphrase to use for encrypting/decrypting the credentials file, either from the
+// `SAFE_CLI_PASSPHRASE` env var, or by prompting the user interactively.
+fn obtain_passphrase() -> Result<String, String> {
+    if let Ok(passphrase) = env::var(SAFE_CLI_PASSPHRASE_ENV_VAR) {
+        return Ok(passphrase);
+    }
+
+    rpassword::prompt_password_stdout("Credentials passphrase: ")
+        .map_err(|err| format!("Failed to read passphrase: {}", err))
+}
+
+// Derive a 32-byte symmetric key from a user passphrase and a salt, using the memory-hard
+// argon2id KDF.
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32], String> {
+    let config = argon2::Config {
+        variant: argon2::Variant::Argon2id,
+        ..argon2::Config::default()
+    };
+    let derived = argon2::hash_raw(passphrase.as_bytes(), salt, &config)
+        .map_err(|err| format!("Failed to derive encryption key from passphrase: {}", err))?;
+
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&derived[..32]);
+    Ok(key)
+}
+
+// Encrypt `plaintext` with a key derived from `passphrase`, returning
+// `magic || version || salt || nonce || ciphertext`.
+fn encrypt_credentials(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>, String> {
+    let mut salt = [0u8; SALT_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let mut nonce_bytes = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce_bytes);
+
+    let key = derive_key(passphrase, &salt)?;
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(&key));
+    let nonce = XNonce::from_slice(&nonce_bytes);
+    let ciphertext = cipher
+        .encrypt(nonce, plaintext)
+        .map_err(|err| format!("Failed to encrypt credentials: {}", err))?;
+
+    let mut output = Vec::with_capacity(4 + 1 + SALT_LEN + NONCE_LEN + ciphertext.len());
+    output.extend_from_slice(ENCRYPTED_FILE_MAGIC);
+    output.push(ENCRYPTED_FILE_VERSION);
+    output.extend_from_slice(&salt);
+    output.extend_from_slice(&nonce_bytes);
+    output.extend_from_slice(&ciphertext);
+    Ok(output)
+}
+
+// Decrypt a file previously produced by `encrypt_credentials`, failing closed (returning an
+// error) if the passphrase is wrong or the data has been tampered with.
+fn decrypt_credentials(data: &[u8], passphrase: &str) -> Result<Vec<u8>, String> {
+    let header_len = ENCRYPTED_FILE_MAGIC.len() + 1;
+    if data.len() < header_len + SALT_LEN + NONCE_LEN {
+        return Err("Credentials file is truncated or corrupted".to_string());
+    }
+
+    let version = data[ENCRYPTED_FILE_MAGIC.len()];
+    if version != ENCRYPTED_FILE_VERSION {
+        return Err(format!(
+            "Unsupported encrypted credentials file version: {}",
+            version
+        ));
+    }
+
+    let salt = &data[header_len..header_len + SALT_LEN];
+    let nonce_bytes = &data[header_len + SALT_LEN..header_len + SALT_LEN + NONCE_LEN];
+    let ciphertext = &data[header_len + SALT_LEN + NONCE_LEN..];
+
+    let key = derive_key(passphrase, salt)?;
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(&key));
+    let nonce = XNonce::from_slice(nonce_bytes);
+    cipher
+        .decrypt(nonce, ciphertext)
+        .map_err(|_| "Failed to decrypt credentials: wrong passphrase or corrupted file".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_decrypt_round_trip() {
+        let plaintext = b"top secret keypair bytes";
+        let encrypted = encrypt_credentials(plaintext, "correct horse battery staple").unwrap();
+        assert!(encrypted.starts_with(ENCRYPTED_FILE_MAGIC));
+
+        let decrypted =
+            decrypt_credentials(&encrypted, "correct horse battery staple").unwrap();
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn decrypt_fails_with_wrong_passphrase() {
+        let encrypted = encrypt_credentials(b"top secret keypair bytes", "the right passphrase")
+            .unwrap();
+
+        assert!(decrypt_credentials(&encrypted, "the wrong passphrase").is_err());
+    }
+
+    #[test]
+    fn decrypt_fails_on_tampered_ciphertext() {
+        let mut encrypted = encrypt_credentials(b"top secret keypair bytes", "a passphrase").unwrap();
+        let last = encrypted.len() - 1;
+        encrypted[last] ^= 0xff;
+
+        assert!(decrypt_credentials(&encrypted, "a passphrase").is_err());
+    }
+
+    #[test]
+    fn decrypt_fails_on_truncated_data() {
+        assert!(decrypt_credentials(b"SAFE", "whatever").is_err());
+    }
+
+    #[test]
+    fn credentials_storage_round_trip() {
+        let raw_credentials = "opaque-wire-format-credentials-blob";
+        let stored = wrap_credentials_for_storage(raw_credentials).unwrap();
+        // The stored form is valid JSON (a quoted string), not the bare opaque string.
+        assert_ne!(stored, raw_credentials);
+        assert_eq!(unwrap_stored_credentials(&stored).unwrap(), raw_credentials);
+    }
+
+    #[test]
+    fn unwrap_stored_credentials_rejects_unquoted_input() {
+        // A raw wire-format string handed to `refresh_auth`/`Safe::connect` without having been
+        // through `wrap_credentials_for_storage` first is not valid JSON, so this must fail
+        // loudly rather than silently forwarding a double-encoded value to authd.
+        assert!(unwrap_stored_credentials("opaque-wire-format-credentials-blob").is_err());
+    }
+
+    #[test]
+    fn is_auth_expiry_error_matches_expiry_and_revocation() {
+        assert!(is_auth_expiry_error("Credentials have expired"));
+        assert!(is_auth_expiry_error("Application was revoked"));
+        assert!(is_auth_expiry_error("app is unauthorised"));
+    }
+
+    #[test]
+    fn is_auth_expiry_error_ignores_unrelated_authd_mentions() {
+        // A bare "auth" substring match would also fire here, triggering an unnecessary refresh
+        // round-trip for what is actually a connectivity problem.
+        assert!(!is_auth_expiry_error("authd not reachable"));
+        assert!(!is_auth_expiry_error(
+            "Failed to connect to the Authenticator: connection refused"
+        ));
+    }
+
+    // `resolve_profile` and the granted-permissions sidecar both read `$HOME` (via
+    // `dirs_next::home_dir`), so these run as a single test against a private temp `$HOME`
+    // rather than as separate `#[test]`s, to avoid two tests racing over the same env var.
+    #[test]
+    fn profile_resolution_and_granted_permissions_round_trip() {
+        let home = env::temp_dir().join(format!(
+            "safe-cli-test-home-{:?}",
+            std::thread::current().id()
+        ));
+        create_dir_all(&home).unwrap();
+        env::set_var("HOME", &home);
+        env::remove_var(SAFE_CLI_PROFILE_ENV_VAR);
+
+        // Explicit profile wins over everything else.
+        assert_eq!(
+            resolve_profile(Some("explicit".to_string())).unwrap(),
+            "explicit"
+        );
+
+        // With no explicit profile and no marker file, the env var wins.
+        env::set_var(SAFE_CLI_PROFILE_ENV_VAR, "from-env");
+        assert_eq!(resolve_profile(None).unwrap(), "from-env");
+
+        // With the env var set, an explicit profile still beats it.
+        assert_eq!(
+            resolve_profile(Some("explicit".to_string())).unwrap(),
+            "explicit"
+        );
+        env::remove_var(SAFE_CLI_PROFILE_ENV_VAR);
+
+        // With neither explicit nor env var set, fall back to `default` when no marker exists.
+        assert_eq!(resolve_profile(None).unwrap(), DEFAULT_PROFILE_NAME);
+
+        // Selecting a profile writes the marker, which is then picked up as the default.
+        select_profile("work").unwrap();
+        assert_eq!(resolve_profile(None).unwrap(), "work");
+
+        // The env var still takes precedence over the marker file.
+        env::set_var(SAFE_CLI_PROFILE_ENV_VAR, "from-env");
+        assert_eq!(resolve_profile(None).unwrap(), "from-env");
+        env::remove_var(SAFE_CLI_PROFILE_ENV_VAR);
+
+        // Round-trip the granted-permissions sidecar for two distinct profiles, verifying
+        // neither clobbers the other's file (the bug a naive `with_extension` hits).
+        let mut work_containers = std::collections::HashMap::new();
+        work_containers.insert(
+            "_public".to_string(),
+            [sn_api::ipc::Permission::Read].iter().copied().collect(),
+        );
+        let work_permissions = AppPermissions {
+            containers: work_containers,
+            app_container: false,
+        };
+        let personal_permissions = AppPermissions {
+            containers: std::collections::HashMap::new(),
+            app_container: true,
+        };
+        write_granted_permissions("work", &work_permissions).unwrap();
+        write_granted_permissions("personal", &personal_permissions).unwrap();
+
+        assert_eq!(
+            read_granted_permissions("work").unwrap(),
+            Some(work_permissions)
+        );
+        assert_eq!(
+            read_granted_permissions("personal").unwrap(),
+            Some(personal_permissions)
+        );
+        assert_eq!(read_granted_permissions("untouched").unwrap(), None);
+
+        // Only a `credentials.<profile>` file makes a profile show up; sidecars alone don't.
+        create_credentials_file("work").unwrap();
+        assert!(list_profiles().unwrap().contains(&"work".to_string()));
+        assert!(!list_profiles().unwrap().contains(&"permissions".to_string()));
+
+        env::remove_var("HOME");
+        let _ = std::fs::remove_dir_all(&home);
+    }
+}